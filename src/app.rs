@@ -0,0 +1,570 @@
+//! Shared CLI implementation, compiled once as part of the library crate and exposed via
+//! [`run`]. Both `src/main.rs` and `src/bin/script.rs` just call `instagram_image_auto_cropper::run()`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+use clap::{Parser, ValueEnum};
+use walkdir::WalkDir;
+use image::{DynamicImage, GenericImageView, ImageOutputFormat, imageops};
+use exif::{Reader, Tag};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::processor::{self, WatermarkCache, crop_to_aspect_center, crop_to_aspect_smart, parse_hex_color};
+
+/// Sidecar file, stored inside `out_dir`, mapping each source file's path relative to
+/// `in_dir` to the hash of the inputs (source bytes + processing params) that produced its output.
+const CACHE_FILE_NAME: &str = ".cropcache";
+
+/// How long a candidate file's size/mtime must be unchanged across polls
+/// before `--watch` treats it as fully written and dispatches it.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+const INSTAGRAM_WIDTH: u32 = 1080;
+const INSTAGRAM_HORIZONTAL_HEIGHT: u32 = 566;
+const INSTAGRAM_VERTICAL_HEIGHT: u32 = 1350;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Crop/resize images for Instagram (auto/vertical/horizontal)")]
+struct Args {
+    #[arg(short, long)]
+    in_dir: PathBuf,
+
+    #[arg(short, long)]
+    out_dir: PathBuf,
+
+    #[arg(short, long, value_enum, default_value_t = Mode::Auto)]
+    mode: Mode,
+
+    #[arg(long, value_enum, default_value_t = ResizeMode::Fill)]
+    resize: ResizeMode,
+
+    /// Hex color (e.g. "#000000") used to pad the canvas when --resize=pad.
+    #[arg(long, default_value = "#000000")]
+    pad_color: String,
+
+    /// Crop window selection strategy, used when --resize=fill.
+    #[arg(long, value_enum, default_value_t = CropStrategy::Center)]
+    crop: CropStrategy,
+
+    #[arg(long, default_value = "keep")]
+    format: String,
+
+    #[arg(long, default_value_t = 100)]
+    quality: u8,
+
+    /// Encode WebP output losslessly instead of at --quality (ignored for other formats).
+    #[arg(long, default_value_t = false)]
+    lossless: bool,
+
+    #[arg(long, default_value_t = 0)]
+    threads: usize,
+
+    /// Reprocess every file even if a cached output with a matching hash exists.
+    #[arg(long, default_value_t = false)]
+    force: bool,
+
+    /// Keep running and crop new files as they land in in_dir (e.g. a camera sync folder).
+    #[arg(long, default_value_t = false)]
+    watch: bool,
+
+    /// Ordered, comma-separated processor pipeline, e.g. "fit,sharpen,watermark:logo.png".
+    /// When set, this replaces --resize/--crop entirely.
+    #[arg(long)]
+    ops: Option<String>,
+
+    /// Write a JSON array describing every processed image to this path.
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+
+    /// Skip files whose aspect ratio is already within ASPECT_TOLERANCE of the
+    /// target, avoiding the decode/encode round-trip entirely.
+    #[arg(long, default_value_t = false)]
+    skip_conforming: bool,
+}
+
+/// How far off (as an absolute difference of width/height ratios) a source image's own
+/// aspect may be from the target's for `--skip-conforming` to still treat it as already-cropped.
+const ASPECT_TOLERANCE: f32 = 0.01;
+
+/// One entry of the `--manifest` JSON array: what was done to a single source image.
+#[derive(Serialize, Debug)]
+struct ManifestRecord {
+    source: PathBuf,
+    output: PathBuf,
+    original_width: u32,
+    original_height: u32,
+    final_width: u32,
+    final_height: u32,
+    mode: String,
+    resize: String,
+    format: String,
+    quality: u8,
+    cropped: bool,
+    padded: bool,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum Mode {
+    Auto,
+    Vertical,
+    Horizontal,
+}
+
+/// How an image is fit into the target Instagram aspect ratio.
+#[derive(ValueEnum, Clone, Debug)]
+enum ResizeMode {
+    /// Crop to the target aspect (window chosen by `--crop`), then resize exact (the default).
+    Fill,
+    /// Scale so the whole image fits inside the target box, preserving aspect; no pixels lost.
+    Fit,
+    /// Like `Fit`, then composite onto a solid-color canvas of the exact target size, centered.
+    Pad,
+}
+
+/// How the crop window is chosen within the source image when `--resize=fill`.
+#[derive(ValueEnum, Clone, Debug)]
+enum CropStrategy {
+    /// Always take the centered window (current behavior).
+    Center,
+    /// Slide the window along the croppable axis and keep the one with the most visual detail.
+    Smart,
+}
+
+/// Loads the `<relpath> <hash>` sidecar manifest from a previous run, if any.
+fn load_cache(out_dir: &Path) -> HashMap<String, u64> {
+    let mut cache = HashMap::new();
+    if let Ok(contents) = fs::read_to_string(out_dir.join(CACHE_FILE_NAME)) {
+        for line in contents.lines() {
+            if let Some((rel, hash)) = line.rsplit_once(' ') {
+                if let Ok(hash) = hash.parse::<u64>() {
+                    cache.insert(rel.to_string(), hash);
+                }
+            }
+        }
+    }
+    cache
+}
+
+fn save_cache(out_dir: &Path, cache: &HashMap<String, u64>) -> anyhow::Result<()> {
+    let mut contents = String::new();
+    for (rel, hash) in cache {
+        contents.push_str(&format!("{} {}\n", rel, hash));
+    }
+    fs::write(out_dir.join(CACHE_FILE_NAME), contents)?;
+    Ok(())
+}
+
+/// Hashes the source file bytes together with the processing params that affect
+/// its output, so a changed flag (mode, crop, quality, ...) invalidates the cache.
+fn compute_hash(bytes: &[u8], args: &Args) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    format!("{:?}", args.mode).hash(&mut hasher);
+    format!("{:?}", args.resize).hash(&mut hasher);
+    format!("{:?}", args.crop).hash(&mut hasher);
+    args.pad_color.hash(&mut hasher);
+    args.format.hash(&mut hasher);
+    args.quality.hash(&mut hasher);
+    args.lossless.hash(&mut hasher);
+    args.ops.hash(&mut hasher);
+    args.skip_conforming.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Encodes `img` as WebP via the dedicated `webp` crate, since `image`'s own
+/// `ImageOutputFormat::WebP` writer only ever produces lossless output and
+/// ignores `--quality` entirely. `quality` is 0-100, matching `--quality`.
+fn encode_webp(img: &DynamicImage, quality: u8, lossless: bool) -> anyhow::Result<Vec<u8>> {
+    let encoder = webp::Encoder::from_image(img).map_err(|e| anyhow::anyhow!("{e}"))?;
+    let memory = if lossless {
+        encoder.encode_lossless()
+    } else {
+        encoder.encode(quality as f32)
+    };
+    Ok(memory.to_vec())
+}
+
+/// Reads just the dimensions (and detected format) of an image without decoding
+/// its pixels, via `image::io::Reader::with_guessed_format` + `into_dimensions`.
+/// Used to pick the `Auto` mode target and, with `--skip-conforming`, to bail
+/// out before the costly full decode/encode round-trip.
+fn probe_dimensions(path: &Path) -> anyhow::Result<(u32, u32)> {
+    let dims = image::io::Reader::open(path)?.with_guessed_format()?.into_dimensions()?;
+    Ok(dims)
+}
+
+/// Like `probe_dimensions`, but swaps width/height when the EXIF orientation tag (read via
+/// `read_orientation`) implies a 90°/270° rotation (values 5-8). The raw probe above reads
+/// pre-rotation dimensions, while the full decode further down always runs `fix_orientation`
+/// before cropping/resizing — without this, a portrait phone photo stored landscape-and-rotated
+/// gets classified as `Mode::Auto` horizontal (or wrongly passes `--skip-conforming`) because
+/// its raw dimensions don't match what the rest of the pipeline actually sees.
+fn probe_effective_dimensions(path: &Path) -> anyhow::Result<(u32, u32)> {
+    let (w, h) = probe_dimensions(path)?;
+    Ok(match read_orientation(path) {
+        5..=8 => (h, w),
+        _ => (w, h),
+    })
+}
+
+/// Resolves the output path and extension for `path` under `--format`, without needing
+/// the decoded image. Used both to short-circuit the cache check and, later, to encode.
+fn resolve_out_path(args: &Args, path: &Path, rel: &Path) -> (PathBuf, String) {
+    let mut out_path = args.out_dir.join(rel);
+    let out_format = args.format.to_lowercase();
+    let ext = if out_format == "jpeg" || out_format == "jpg" {
+        "jpg".to_string()
+    } else if out_format == "png" {
+        "png".to_string()
+    } else if out_format == "webp" {
+        "webp".to_string()
+    } else {
+        match path.extension().and_then(|s| s.to_str()) {
+            Some(e) => e.to_lowercase(),
+            None => "jpg".to_string(),
+        }
+    };
+    out_path.set_extension(&ext);
+    (out_path, ext)
+}
+
+pub fn run() -> anyhow::Result<()> {
+    let args = Args::parse();
+
+    if !args.in_dir.exists() {
+        anyhow::bail!("El directorio de entrada no existe: {}", args.in_dir.display());
+    }
+    if !args.in_dir.is_dir() {
+        anyhow::bail!("La ruta de entrada no es un directorio: {}", args.in_dir.display());
+    }
+
+    if args.threads > 0 {
+        rayon::ThreadPoolBuilder::new().num_threads(args.threads).build_global()?;
+    }
+
+    fs::create_dir_all(&args.out_dir)?;
+
+    let walker = find_image_files(&args.in_dir);
+
+    println!("Procesando {} archivos...", walker.len());
+
+    let cache = Mutex::new(load_cache(&args.out_dir));
+    let watermark_cache: WatermarkCache = Mutex::new(HashMap::new());
+
+    let mut records = dispatch(&walker, &args, &cache, &watermark_cache);
+
+    if let Some(manifest_path) = &args.manifest {
+        let json = serde_json::to_vec_pretty(&records)?;
+        fs::write(manifest_path, json)?;
+    }
+
+    if args.watch {
+        println!("Modo watch: observando {} ...", args.in_dir.display());
+        watch_loop(&args, &cache, &watermark_cache, &mut records)?;
+    }
+
+    save_cache(&args.out_dir, &cache.into_inner().unwrap())?;
+
+    println!("Listo. Archivos procesados en: {}", args.out_dir.display());
+    Ok(())
+}
+
+fn is_image_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .map(|ext| matches!(ext.to_lowercase().as_str(), "jpg" | "jpeg" | "png" | "webp" | "tiff" | "bmp"))
+        .unwrap_or(false)
+}
+
+fn find_image_files(in_dir: &Path) -> Vec<PathBuf> {
+    WalkDir::new(in_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|p| is_image_file(p))
+        .collect()
+}
+
+/// Processes every path in parallel and collects the manifest record each worker
+/// returns (skipped/errored files contribute no record) via `map`/`collect` rather
+/// than `for_each`, since the gathered `Vec` is what `--manifest` serializes.
+fn dispatch(
+    paths: &[PathBuf],
+    args: &Args,
+    cache: &Mutex<HashMap<String, u64>>,
+    watermark_cache: &WatermarkCache,
+) -> Vec<ManifestRecord> {
+    paths
+        .par_iter()
+        .filter_map(|path| match process_file(path, args, cache, watermark_cache) {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("Error procesando {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Polls `in_dir` for newly added image files and dispatches each one once its
+/// size/mtime have stayed unchanged across two consecutive polls, so half-written
+/// uploads aren't cropped mid-copy. Runs until the process is killed, so the cache is
+/// saved after every batch rather than just once at the (unreachable) end of the loop —
+/// otherwise a killed watch session would lose every cache update made while it ran.
+/// `records` accumulates every batch's manifest records (seeded with the initial scan's)
+/// and `--manifest` is re-written after each batch, so watching and manifesting combine
+/// instead of the manifest being frozen at the initial scan.
+fn watch_loop(
+    args: &Args,
+    cache: &Mutex<HashMap<String, u64>>,
+    watermark_cache: &WatermarkCache,
+    records: &mut Vec<ManifestRecord>,
+) -> anyhow::Result<()> {
+    let mut pending: HashMap<PathBuf, (u64, SystemTime)> = HashMap::new();
+    let mut known: std::collections::HashSet<PathBuf> = find_image_files(&args.in_dir).into_iter().collect();
+
+    loop {
+        std::thread::sleep(WATCH_POLL_INTERVAL);
+
+        let seen = find_image_files(&args.in_dir);
+        let mut ready = Vec::new();
+
+        for path in &seen {
+            if known.contains(path) {
+                continue;
+            }
+            let meta = match fs::metadata(path) {
+                Ok(m) => m,
+                Err(_) => continue,
+            };
+            let modified = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            let stamp = (meta.len(), modified);
+
+            match pending.get(path) {
+                Some(prev) if *prev == stamp => {
+                    pending.remove(path);
+                    ready.push(path.clone());
+                }
+                _ => {
+                    pending.insert(path.clone(), stamp);
+                }
+            }
+        }
+
+        if !ready.is_empty() {
+            records.extend(dispatch(&ready, args, cache, watermark_cache));
+            known.extend(ready);
+            save_cache(&args.out_dir, &cache.lock().unwrap())?;
+
+            if let Some(manifest_path) = &args.manifest {
+                let json = serde_json::to_vec_pretty(records)?;
+                fs::write(manifest_path, json)?;
+            }
+        }
+    }
+}
+
+fn process_file(
+    path: &Path,
+    args: &Args,
+    cache: &Mutex<HashMap<String, u64>>,
+    watermark_cache: &WatermarkCache,
+) -> anyhow::Result<Option<ManifestRecord>> {
+    let rel = path.strip_prefix(&args.in_dir).unwrap_or(path);
+    let rel_key = rel.to_string_lossy().into_owned();
+
+    let bytes = fs::read(path)?;
+    let hash = compute_hash(&bytes, args);
+
+    let (pw, ph) = probe_effective_dimensions(path)?;
+
+    let mode = match args.mode {
+        Mode::Auto => {
+            if pw >= ph { Mode::Horizontal } else { Mode::Vertical }
+        }
+        ref other => other.clone(),
+    };
+
+    let (target_w, target_h) = match mode {
+        Mode::Horizontal => (INSTAGRAM_WIDTH, INSTAGRAM_HORIZONTAL_HEIGHT),
+        Mode::Vertical => (INSTAGRAM_WIDTH, INSTAGRAM_VERTICAL_HEIGHT),
+        Mode::Auto => unreachable!(),
+    };
+
+    let target_aspect = target_w as f32 / target_h as f32;
+    let source_aspect = pw as f32 / ph as f32;
+    let conforming = args.skip_conforming && (source_aspect - target_aspect).abs() <= ASPECT_TOLERANCE;
+
+    // A conforming file is passed through untouched, so it keeps its own extension rather than
+    // going through --format conversion, which only applies to files that actually get processed.
+    let (out_path, ext) = if conforming {
+        let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("jpg").to_lowercase();
+        (args.out_dir.join(rel), ext)
+    } else {
+        resolve_out_path(args, path, rel)
+    };
+
+    if !args.force {
+        if cache.lock().unwrap().get(&rel_key) == Some(&hash) && out_path.exists() {
+            println!("SKIP (sin cambios): {}", path.display());
+            return Ok(None);
+        }
+    }
+
+    if conforming {
+        if let Some(p) = out_path.parent() {
+            fs::create_dir_all(p)?;
+        }
+        fs::copy(path, &out_path)?;
+        cache.lock().unwrap().insert(rel_key, hash);
+        println!("OK (ya conforme): {} -> {} ({}x{})", path.display(), out_path.display(), pw, ph);
+        return Ok(Some(ManifestRecord {
+            source: path.to_path_buf(),
+            output: out_path,
+            original_width: pw,
+            original_height: ph,
+            final_width: pw,
+            final_height: ph,
+            mode: format!("{:?}", mode),
+            resize: args.ops.clone().unwrap_or_else(|| format!("{:?}", args.resize)),
+            format: ext,
+            quality: args.quality,
+            cropped: false,
+            padded: false,
+        }));
+    }
+
+    let img_orig = image::load_from_memory(&bytes)?;
+
+    let img = match fix_orientation(path, &img_orig) {
+        Ok(i) => i,
+        Err(_) => img_orig,
+    };
+
+    let (w, h) = img.dimensions();
+
+    let (final_img, cropped, padded) = if let Some(ops) = &args.ops {
+        let pad_color = parse_hex_color(&args.pad_color)?;
+        let (pipeline, cropped, padded) =
+            processor::parse_pipeline(ops, target_w, target_h, pad_color, watermark_cache)?;
+        let result = pipeline.into_iter().try_fold(img, |acc, op| op.process(acc))?;
+        (result, cropped, padded)
+    } else {
+        match args.resize {
+            ResizeMode::Fill => {
+                let img_cropped = match args.crop {
+                    CropStrategy::Center => crop_to_aspect_center(&img, target_aspect),
+                    CropStrategy::Smart => crop_to_aspect_smart(&img, target_aspect),
+                };
+                let (cw, ch) = img_cropped.dimensions();
+                let (final_w, final_h) = if cw < target_w || ch < target_h {
+                    (cw, ch)
+                } else {
+                    (target_w, target_h)
+                };
+                (img_cropped.resize_exact(final_w, final_h, imageops::FilterType::Lanczos3), true, false)
+            }
+            ResizeMode::Fit => (img.resize(target_w, target_h, imageops::FilterType::Lanczos3), false, false),
+            ResizeMode::Pad => {
+                let fitted = img.resize(target_w, target_h, imageops::FilterType::Lanczos3);
+                let pad_color = parse_hex_color(&args.pad_color)?;
+                let mut canvas = DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(target_w, target_h, pad_color));
+                let (fw, fh) = fitted.dimensions();
+                let x = (target_w - fw) / 2;
+                let y = (target_h - fh) / 2;
+                imageops::overlay(&mut canvas, &fitted, x as i64, y as i64);
+                (canvas, false, true)
+            }
+        }
+    };
+
+    if let Some(p) = out_path.parent() {
+        fs::create_dir_all(p)?;
+    }
+
+    let (final_w, final_h) = final_img.dimensions();
+
+    if ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg") {
+        let rgb = match final_img {
+            DynamicImage::ImageRgba8(img) => DynamicImage::ImageRgb8(image::DynamicImage::ImageRgba8(img).to_rgb8()),
+            DynamicImage::ImageRgba16(img) => DynamicImage::ImageRgb16(image::DynamicImage::ImageRgba16(img).to_rgb16()),
+            other => other.to_rgb8().into(),
+        };
+        let mut out_file = fs::File::create(&out_path)?;
+        rgb.write_to(&mut out_file, ImageOutputFormat::Jpeg(args.quality))?;
+    } else if ext.eq_ignore_ascii_case("png") {
+        let mut out_file = fs::File::create(&out_path)?;
+        final_img.write_to(&mut out_file, ImageOutputFormat::Png)?;
+    } else if ext.eq_ignore_ascii_case("webp") {
+        let bytes = encode_webp(&final_img, args.quality, args.lossless)?;
+        fs::write(&out_path, bytes)?;
+    } else {
+        let mut out_file = fs::File::create(&out_path)?;
+        final_img.write_to(&mut out_file, ImageOutputFormat::Png)?;
+    }
+
+    cache.lock().unwrap().insert(rel_key, hash);
+
+    println!("OK: {} -> {} ({}x{})", path.display(), out_path.display(), final_w, final_h);
+    Ok(Some(ManifestRecord {
+        source: path.to_path_buf(),
+        output: out_path,
+        original_width: w,
+        original_height: h,
+        final_width: final_w,
+        final_height: final_h,
+        mode: format!("{:?}", mode),
+        resize: args.ops.clone().unwrap_or_else(|| format!("{:?}", args.resize)),
+        format: ext.to_string(),
+        quality: args.quality,
+        cropped,
+        padded,
+    }))
+}
+
+/// Reads the EXIF orientation tag (1-8) from `path`, defaulting to 1 (no transform needed)
+/// whenever the file can't be opened, has no EXIF container, or lacks the tag. Shared by
+/// `fix_orientation` (applied to the decoded image) and `probe_effective_dimensions` (applied
+/// to the raw, pre-decode dimensions), so both agree on what "rotated" means for a given file.
+fn read_orientation(path: &Path) -> u16 {
+    use std::fs::File;
+    use std::io::BufReader;
+
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(_) => return 1,
+    };
+    let mut bufreader = BufReader::new(&file);
+    let exifreader = Reader::new();
+    let exif = match exifreader.read_from_container(&mut bufreader) {
+        Ok(e) => e,
+        Err(_) => return 1,
+    };
+    exif.get_field(Tag::Orientation, exif::In::PRIMARY)
+        .and_then(|field| match field.value {
+            exif::Value::Short(ref vec) => vec.get(0).copied(),
+            _ => None,
+        })
+        .unwrap_or(1)
+}
+
+fn fix_orientation(path: &Path, img: &DynamicImage) -> anyhow::Result<DynamicImage> {
+    Ok(match read_orientation(path) {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => img.clone(),
+    })
+}
+
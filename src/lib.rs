@@ -0,0 +1,10 @@
+//! Shared library backing both `src/main.rs` and `src/bin/script.rs`. Each binary is a thin
+//! `fn main()` calling [`run`] — the actual CLI implementation lives in `app`/`processor` and
+//! is compiled once here, instead of each binary crate root re-parsing copies of the same files
+//! via `#[path = ...] mod` (which previously let `app.rs` and its sibling `processor.rs` drift
+//! out of scope-resolution sync with each other).
+
+mod app;
+mod processor;
+
+pub use app::run;
@@ -0,0 +1,325 @@
+//! Composable image operations, selected at runtime via `--ops "fit,sharpen,watermark:logo.png"`.
+//!
+//! Each comma-separated token in `--ops` is parsed into a boxed [`Processor`]; `process_file`
+//! folds the source image through the resulting chain before encoding,
+//! instead of always running the fixed orientation -> crop -> resize -> encode sequence.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use image::{DynamicImage, GenericImageView, Rgba, imageops};
+
+pub trait Processor {
+    fn process(&self, img: DynamicImage) -> anyhow::Result<DynamicImage>;
+}
+
+/// Memoizes decoded watermark images by path, so a `--ops watermark:logo.png` pipeline
+/// applied to a whole batch decodes `logo.png` once instead of once per file.
+pub type WatermarkCache = Mutex<HashMap<PathBuf, Arc<DynamicImage>>>;
+
+pub fn parse_hex_color(hex: &str) -> anyhow::Result<Rgba<u8>> {
+    let hex = hex.trim_start_matches('#');
+    if hex.len() != 6 {
+        anyhow::bail!("Color hex inválido: {}", hex);
+    }
+    let r = u8::from_str_radix(&hex[0..2], 16)?;
+    let g = u8::from_str_radix(&hex[2..4], 16)?;
+    let b = u8::from_str_radix(&hex[4..6], 16)?;
+    Ok(Rgba([r, g, b, 255]))
+}
+
+/// Crops the center of `img` down to `target_aspect`, leaving the croppable axis untouched
+/// if the image already matches (no-op).
+pub fn crop_to_aspect_center(img: &DynamicImage, target_aspect: f32) -> DynamicImage {
+    let (w, h) = img.dimensions();
+    let img_aspect = w as f32 / h as f32;
+
+    if (img_aspect - target_aspect).abs() < 1e-6 {
+        return img.clone();
+    }
+
+    if img_aspect > target_aspect {
+        let new_w = (target_aspect * h as f32).round() as u32;
+        let x0 = (w - new_w) / 2;
+        img.crop_imm(x0, 0, new_w, h)
+    } else {
+        let new_h = (w as f32 / target_aspect).round() as u32;
+        let y0 = (h - new_h) / 2;
+        img.crop_imm(0, y0, w, new_h)
+    }
+}
+
+/// Computes Sobel gradient magnitude (`|Gx| + |Gy|`) for every interior pixel of `gray`.
+/// Unlike `imageops::filter3x3` with a single-direction kernel, this combines both the
+/// horizontal and vertical Sobel responses and accumulates them in `u64` rather than
+/// clamping to `u8`, so neither gradient direction nor negative responses are lost.
+/// Border pixels (no full 3x3 neighborhood) are left at zero energy.
+fn sobel_magnitude(gray: &image::GrayImage) -> Vec<u64> {
+    const GX: [i32; 9] = [-1, 0, 1, -2, 0, 2, -1, 0, 1];
+    const GY: [i32; 9] = [-1, -2, -1, 0, 0, 0, 1, 2, 1];
+
+    let (w, h) = gray.dimensions();
+    let mut energy = vec![0u64; (w as usize) * (h as usize)];
+
+    if w < 3 || h < 3 {
+        return energy;
+    }
+
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let mut gx = 0i32;
+            let mut gy = 0i32;
+            let mut k = 0usize;
+            for dy in -1i32..=1 {
+                for dx in -1i32..=1 {
+                    let p = gray.get_pixel((x as i32 + dx) as u32, (y as i32 + dy) as u32)[0] as i32;
+                    gx += p * GX[k];
+                    gy += p * GY[k];
+                    k += 1;
+                }
+            }
+            energy[(y * w + x) as usize] = (gx.unsigned_abs() + gy.unsigned_abs()) as u64;
+        }
+    }
+
+    energy
+}
+
+/// Picks the crop window along the croppable axis that maximizes visual detail
+/// (Sobel gradient magnitude), instead of always taking the centered slice.
+pub fn crop_to_aspect_smart(img: &DynamicImage, target_aspect: f32) -> DynamicImage {
+    let (w, h) = img.dimensions();
+    let img_aspect = w as f32 / h as f32;
+
+    if (img_aspect - target_aspect).abs() < 1e-6 {
+        return img.clone();
+    }
+
+    let gray = img.to_luma8();
+    let energy = sobel_magnitude(&gray);
+
+    let mut integral = vec![0u64; (w as usize + 1) * (h as usize + 1)];
+    let stride = w as usize + 1;
+    for y in 0..h as usize {
+        let mut row_sum = 0u64;
+        for x in 0..w as usize {
+            row_sum += energy[y * w as usize + x];
+            integral[(y + 1) * stride + (x + 1)] = integral[y * stride + (x + 1)] + row_sum;
+        }
+    }
+    let window_energy = |x0: u32, y0: u32, ww: u32, wh: u32| -> u64 {
+        let (x0, y0, ww, wh) = (x0 as usize, y0 as usize, ww as usize, wh as usize);
+        integral[(y0 + wh) * stride + (x0 + ww)]
+            - integral[y0 * stride + (x0 + ww)]
+            - integral[(y0 + wh) * stride + x0]
+            + integral[y0 * stride + x0]
+    };
+
+    if img_aspect > target_aspect {
+        let new_w = (target_aspect * h as f32).round() as u32;
+        let max_x0 = w - new_w;
+        let best_x0 = (0..=max_x0)
+            .max_by_key(|&x0| window_energy(x0, 0, new_w, h))
+            .unwrap_or(0);
+        img.crop_imm(best_x0, 0, new_w, h)
+    } else {
+        let new_h = (w as f32 / target_aspect).round() as u32;
+        let max_y0 = h - new_h;
+        let best_y0 = (0..=max_y0)
+            .max_by_key(|&y0| window_energy(0, y0, w, new_h))
+            .unwrap_or(0);
+        img.crop_imm(0, best_y0, w, new_h)
+    }
+}
+
+/// Crops the center to `target_w`x`target_h`'s aspect, then resizes exact (the `Fill` behavior),
+/// optionally using the detail-maximizing (Sobel energy) smart crop window instead of the center.
+struct Crop {
+    target_w: u32,
+    target_h: u32,
+    smart: bool,
+}
+
+impl Processor for Crop {
+    fn process(&self, img: DynamicImage) -> anyhow::Result<DynamicImage> {
+        let target_aspect = self.target_w as f32 / self.target_h as f32;
+        let cropped = if self.smart {
+            crop_to_aspect_smart(&img, target_aspect)
+        } else {
+            crop_to_aspect_center(&img, target_aspect)
+        };
+        let (cw, ch) = cropped.dimensions();
+        let (w, h) = if cw < self.target_w || ch < self.target_h {
+            (cw, ch)
+        } else {
+            (self.target_w, self.target_h)
+        };
+        Ok(cropped.resize_exact(w, h, imageops::FilterType::Lanczos3))
+    }
+}
+
+/// Scales so the whole image fits inside the target box, preserving aspect (the `Fit` behavior).
+struct Resize {
+    target_w: u32,
+    target_h: u32,
+}
+
+impl Processor for Resize {
+    fn process(&self, img: DynamicImage) -> anyhow::Result<DynamicImage> {
+        Ok(img.resize(self.target_w, self.target_h, imageops::FilterType::Lanczos3))
+    }
+}
+
+/// `Fit`, then composited onto a solid-color canvas of the exact target size, centered.
+struct Pad {
+    target_w: u32,
+    target_h: u32,
+    color: Rgba<u8>,
+}
+
+impl Processor for Pad {
+    fn process(&self, img: DynamicImage) -> anyhow::Result<DynamicImage> {
+        let fitted = img.resize(self.target_w, self.target_h, imageops::FilterType::Lanczos3);
+        let mut canvas =
+            DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(self.target_w, self.target_h, self.color));
+        let (fw, fh) = fitted.dimensions();
+        let x = (self.target_w - fw) / 2;
+        let y = (self.target_h - fh) / 2;
+        imageops::overlay(&mut canvas, &fitted, x as i64, y as i64);
+        Ok(canvas)
+    }
+}
+
+struct Grayscale;
+
+impl Processor for Grayscale {
+    fn process(&self, img: DynamicImage) -> anyhow::Result<DynamicImage> {
+        Ok(img.grayscale())
+    }
+}
+
+struct Sharpen;
+
+impl Processor for Sharpen {
+    fn process(&self, img: DynamicImage) -> anyhow::Result<DynamicImage> {
+        Ok(img.unsharpen(1.0, 10))
+    }
+}
+
+struct Rotate {
+    degrees: u32,
+}
+
+impl Processor for Rotate {
+    fn process(&self, img: DynamicImage) -> anyhow::Result<DynamicImage> {
+        match self.degrees {
+            90 => Ok(img.rotate90()),
+            180 => Ok(img.rotate180()),
+            270 => Ok(img.rotate270()),
+            other => anyhow::bail!("rotate solo admite 90, 180 o 270 grados (recibido {})", other),
+        }
+    }
+}
+
+struct Watermark {
+    image: Arc<DynamicImage>,
+}
+
+impl Processor for Watermark {
+    fn process(&self, img: DynamicImage) -> anyhow::Result<DynamicImage> {
+        let (iw, ih) = img.dimensions();
+        let (mw, mh) = self.image.dimensions();
+        let mut canvas = img;
+        let x = iw.saturating_sub(mw);
+        let y = ih.saturating_sub(mh);
+        imageops::overlay(&mut canvas, self.image.as_ref(), x as i64, y as i64);
+        Ok(canvas)
+    }
+}
+
+/// Parses a `--ops` spec like `"fit,sharpen,watermark:logo.png"` into an ordered pipeline,
+/// along with whether the spec includes a `crop`/`fill` token and a `pad` token. Those two
+/// flags are derived from the parsed token keys rather than a substring search over `spec`,
+/// so e.g. `--ops "watermark:pad_logo.png"` doesn't falsely report a `pad` step just because
+/// "pad" appears inside a watermark file name. Each comma-separated token is either a bare
+/// key (`fit`, `sharpen`) or `key:value` (`rotate:90`, `watermark:logo.png`). `target_w`/
+/// `target_h` and `default_pad_color` come from the resolved Instagram mode and `--pad-color`,
+/// so `crop`/`fit`/`pad` tokens don't need to repeat the target dimensions on the command line.
+pub fn parse_pipeline(
+    spec: &str,
+    target_w: u32,
+    target_h: u32,
+    default_pad_color: Rgba<u8>,
+    watermark_cache: &WatermarkCache,
+) -> anyhow::Result<(Vec<Box<dyn Processor>>, bool, bool)> {
+    let tokens: Vec<&str> = spec.split(',').map(str::trim).filter(|tok| !tok.is_empty()).collect();
+
+    let cropped = tokens.iter().any(|tok| matches!(token_key(tok), "crop" | "fill"));
+    let padded = tokens.iter().any(|tok| token_key(tok) == "pad");
+
+    let processors = tokens
+        .into_iter()
+        .map(|tok| parse_token(tok, target_w, target_h, default_pad_color, watermark_cache))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok((processors, cropped, padded))
+}
+
+/// The part of a `key` or `key:value` token before the first `:`, used to classify a token
+/// without parsing its value.
+fn token_key(tok: &str) -> &str {
+    tok.split_once(':').map(|(k, _)| k).unwrap_or(tok)
+}
+
+fn parse_token(
+    tok: &str,
+    target_w: u32,
+    target_h: u32,
+    default_pad_color: Rgba<u8>,
+    watermark_cache: &WatermarkCache,
+) -> anyhow::Result<Box<dyn Processor>> {
+    let (key, value) = match tok.split_once(':') {
+        Some((k, v)) => (k, Some(v)),
+        None => (tok, None),
+    };
+
+    match key {
+        "crop" | "fill" => Ok(Box::new(Crop { target_w, target_h, smart: value == Some("smart") })),
+        "fit" => Ok(Box::new(Resize { target_w, target_h })),
+        "pad" => {
+            let color = match value {
+                Some(hex) => parse_hex_color(hex)?,
+                None => default_pad_color,
+            };
+            Ok(Box::new(Pad { target_w, target_h, color }))
+        }
+        "grayscale" => Ok(Box::new(Grayscale)),
+        "sharpen" => Ok(Box::new(Sharpen)),
+        "rotate" => {
+            let degrees = value
+                .ok_or_else(|| anyhow::anyhow!("rotate necesita un valor, ej. rotate:90"))?
+                .parse()?;
+            Ok(Box::new(Rotate { degrees }))
+        }
+        "watermark" => {
+            let path = PathBuf::from(
+                value.ok_or_else(|| anyhow::anyhow!("watermark necesita una ruta, ej. watermark:logo.png"))?,
+            );
+            let cached = watermark_cache.lock().unwrap().get(&path).cloned();
+            let image = match cached {
+                Some(image) => image,
+                None => {
+                    // Decode outside the lock so a miss on one watermark path doesn't block
+                    // other threads decoding a different one; a rare concurrent double-decode
+                    // of the same path is harmless since both insert the same bytes.
+                    let image = Arc::new(image::open(&path)?);
+                    watermark_cache.lock().unwrap().insert(path, Arc::clone(&image));
+                    image
+                }
+            };
+            Ok(Box::new(Watermark { image }))
+        }
+        other => anyhow::bail!("Operación de pipeline desconocida: {}", other),
+    }
+}